@@ -0,0 +1,734 @@
+//
+// rslex - a lexer generator for rust
+//
+// automata.rs
+// Compiling a regexp Ast into a runnable NFA/DFA recognizer, via
+// Thompson's construction followed by subset construction
+//
+// Andrei de A. Formiga, 2013-08-09
+//
+
+extern mod std;
+
+use std::hashmap::{HashMap, HashSet};
+
+use regexp::{Ast, Symb, Str, Union, Conc, Star, OnePlus, Opt, Repeat, CharClass, NegClass, Epsilon};
+use regexp::{ClassItem, Singles, Range, Named};
+
+pub type StateId = uint;
+
+/// Errors raised while compiling an `Ast` into an automaton
+#[deriving(Eq, Clone)]
+pub enum CompileError {
+    UndefinedSymbol(~str),
+    UnknownNamedClass(~str)
+}
+
+// `Cls` carries the resolved (no `Named` items left) set of items the
+// label matches, together with whether the class is negated
+#[deriving(Clone)]
+enum Label { Eps, Sym(char), Cls(~[ClassItem], bool) }
+
+/// An NFA built by Thompson's construction, over one or more rules
+/// unioned under a common start state. Each rule's fragment keeps its
+/// own accept state, tagged with the rule's index so the DFA built
+/// from it can report which rule matched.
+struct Nfa {
+    n_states: uint,
+    trans: ~[(StateId, Label, StateId)],
+    start: StateId,
+    accepts: HashMap<StateId, uint>
+}
+
+impl Nfa {
+    /// Build an NFA recognizing any of `rules`, resolving named `Symb`
+    /// references against `defs`. Rules are tried in order: when an
+    /// input is accepted by more than one rule, the lowest index wins.
+    fn build(rules: &[Ast], defs: &HashMap<~str, Ast>) -> Result<Nfa, CompileError> {
+        let mut n_states = 0u;
+        let mut trans: ~[(StateId, Label, StateId)] = ~[];
+        let mut accepts: HashMap<StateId, uint> = HashMap::new();
+        let top = fresh_state(&mut n_states);
+        for (i, ast) in rules.iter().enumerate() {
+            let (s, a) = try!(build_fragment(ast, defs, &mut n_states, &mut trans));
+            add_trans(&mut trans, top, Eps, s);
+            accepts.insert(a, i);
+        }
+        Ok(Nfa { n_states: n_states, trans: trans, start: top, accepts: accepts })
+    }
+
+    /// The set of states reachable from `states` via epsilon transitions only
+    fn eps_closure(&self, states: &HashSet<StateId>) -> HashSet<StateId> {
+        let mut res = states.clone();
+        let mut stack: ~[StateId] = states.iter().map(|&s| s).collect();
+        loop {
+            match stack.pop() {
+                None => break,
+                Some(s) => {
+                    for &(from, ref lbl, to) in self.trans.iter() {
+                        match *lbl {
+                            Eps if from == s && !res.contains(&to) => {
+                                res.insert(to);
+                                stack.push(to);
+                            }
+                            _ => ()
+                        }
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    /// The set of states reachable from `states` on a single transition
+    /// matching `c` (not including the epsilon-closure of the result)
+    fn step(&self, states: &HashSet<StateId>, c: char) -> HashSet<StateId> {
+        let mut res = HashSet::new();
+        for &(from, ref lbl, to) in self.trans.iter() {
+            if states.contains(&from) && label_matches(lbl, c) {
+                res.insert(to);
+            }
+        }
+        res
+    }
+
+    /// The lowest-indexed rule accepted by any state in `states`, if any
+    fn rule_tag(&self, states: &HashSet<StateId>) -> Option<uint> {
+        let mut best: Option<uint> = None;
+        for s in states.iter() {
+            match self.accepts.find(s) {
+                Some(&rule) => best = match best {
+                    Some(b) if b <= rule => Some(b),
+                    _ => Some(rule)
+                },
+                None => ()
+            }
+        }
+        best
+    }
+}
+
+fn label_matches(lbl: &Label, c: char) -> bool {
+    match *lbl {
+        Eps => false,
+        Sym(lc) => lc == c,
+        Cls(ref items, negated) => class_matches(*items, c) != negated
+    }
+}
+
+fn class_matches(items: &[ClassItem], c: char) -> bool {
+    items.iter().any(|item| match *item {
+        Singles(ref s) => s.contains_char(c),
+        Range(lo, hi) => c >= lo && c <= hi,
+        // `resolve_class_items` resolves every `Named` item before a
+        // class reaches a compiled `Cls` label, so this is unreachable
+        // in practice; treat an unresolved name as matching nothing
+        // rather than aborting the whole match
+        Named(ref name) => match named_class_items(*name) {
+            Some(items) => class_matches(items.as_slice(), c),
+            None => false
+        }
+    })
+}
+
+// Expand a POSIX-style named class (as found inside `[:name:]`) into
+// the concrete items it stands for, or `None` if `name` isn't recognized
+fn named_class_items(name: &str) -> Option<~[ClassItem]> {
+    match name {
+        "digit" => Some(~[Range('0', '9')]),
+        "alpha" => Some(~[Range('a', 'z'), Range('A', 'Z')]),
+        "alnum" => Some(~[Range('a', 'z'), Range('A', 'Z'), Range('0', '9')]),
+        "upper" => Some(~[Range('A', 'Z')]),
+        "lower" => Some(~[Range('a', 'z')]),
+        "space" => Some(~[Singles(~" \t\n\r\x0b\x0c")]),
+        "punct" => Some(~[Singles(~"!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~")]),
+        _ => None
+    }
+}
+
+// Replace every `Named` item with the concrete items it stands for, so
+// the automata backend only ever has to deal with `Singles`/`Range`
+fn resolve_class_items(items: &[ClassItem]) -> Result<~[ClassItem], CompileError> {
+    let mut res: ~[ClassItem] = ~[];
+    for item in items.iter() {
+        match *item {
+            Named(ref name) => match named_class_items(*name) {
+                Some(expanded) => for e in expanded.iter() { res.push(e.clone()) },
+                None => return Err(UnknownNamedClass(name.to_owned()))
+            },
+            ref other => res.push(other.clone())
+        }
+    }
+    Ok(res)
+}
+
+fn fresh_state(n_states: &mut uint) -> StateId {
+    let s = *n_states;
+    *n_states += 1;
+    s
+}
+
+fn add_trans(trans: &mut ~[(StateId, Label, StateId)], from: StateId, lbl: Label, to: StateId) {
+    trans.push((from, lbl, to));
+}
+
+// Build an NFA fragment for `ast`, returning its (start, accept) states
+fn build_fragment(ast: &Ast, defs: &HashMap<~str, Ast>,
+                   n_states: &mut uint, trans: &mut ~[(StateId, Label, StateId)])
+                   -> Result<(StateId, StateId), CompileError> {
+    match *ast {
+        Epsilon => {
+            let s = fresh_state(n_states);
+            let a = fresh_state(n_states);
+            add_trans(trans, s, Eps, a);
+            Ok((s, a))
+        }
+        Symb(ref name) => {
+            match defs.find(name) {
+                Some(def) => build_fragment(def, defs, n_states, trans),
+                None => Err(UndefinedSymbol(name.to_owned()))
+            }
+        }
+        Str(ref s) => {
+            let start = fresh_state(n_states);
+            let mut prev = start;
+            for c in s.chars() {
+                let next = fresh_state(n_states);
+                add_trans(trans, prev, Sym(c), next);
+                prev = next;
+            }
+            if s.is_empty() {
+                let a = fresh_state(n_states);
+                add_trans(trans, start, Eps, a);
+                Ok((start, a))
+            } else {
+                Ok((start, prev))
+            }
+        }
+        CharClass(ref items) => {
+            let resolved = try!(resolve_class_items(*items));
+            let s = fresh_state(n_states);
+            let a = fresh_state(n_states);
+            add_trans(trans, s, Cls(resolved, false), a);
+            Ok((s, a))
+        }
+        NegClass(ref items) => {
+            let resolved = try!(resolve_class_items(*items));
+            let s = fresh_state(n_states);
+            let a = fresh_state(n_states);
+            add_trans(trans, s, Cls(resolved, true), a);
+            Ok((s, a))
+        }
+        Union(ref l, ref r) => {
+            let (ls, la) = try!(build_fragment(*l, defs, n_states, trans));
+            let (rs, ra) = try!(build_fragment(*r, defs, n_states, trans));
+            let s = fresh_state(n_states);
+            let a = fresh_state(n_states);
+            add_trans(trans, s, Eps, ls);
+            add_trans(trans, s, Eps, rs);
+            add_trans(trans, la, Eps, a);
+            add_trans(trans, ra, Eps, a);
+            Ok((s, a))
+        }
+        Conc(ref l, ref r) => {
+            let (ls, la) = try!(build_fragment(*l, defs, n_states, trans));
+            let (rs, ra) = try!(build_fragment(*r, defs, n_states, trans));
+            add_trans(trans, la, Eps, rs);
+            Ok((ls, ra))
+        }
+        Star(ref x) => {
+            let (xs, xa) = try!(build_fragment(*x, defs, n_states, trans));
+            let s = fresh_state(n_states);
+            let a = fresh_state(n_states);
+            add_trans(trans, s, Eps, xs);
+            add_trans(trans, s, Eps, a);
+            add_trans(trans, xa, Eps, xs);
+            add_trans(trans, xa, Eps, a);
+            Ok((s, a))
+        }
+        OnePlus(ref x) => {
+            let desugared = Conc(x.clone(), ~Star(x.clone()));
+            build_fragment(&desugared, defs, n_states, trans)
+        }
+        Opt(ref x) => {
+            let desugared = Union(x.clone(), ~Epsilon);
+            build_fragment(&desugared, defs, n_states, trans)
+        }
+        Repeat(ref x, m, n) => {
+            let mut copies: ~[Ast] = ~[];
+            for _ in range(0, m) { copies.push((**x).clone()) }
+            match n {
+                Some(n) => for _ in range(m, n) { copies.push(Opt(x.clone())) },
+                None => copies.push(Star(x.clone()))
+            }
+            let desugared = if copies.is_empty() {
+                Epsilon
+            } else {
+                let mut it = copies.move_iter();
+                let first = it.next().unwrap();
+                it.fold(first, |acc, nxt| Conc(~acc, ~nxt))
+            };
+            build_fragment(&desugared, defs, n_states, trans)
+        }
+    }
+}
+
+/// A DFA obtained from an `Nfa` by subset construction: each state is
+/// the epsilon-closure of a set of NFA states, tagged with the lowest
+/// rule index it accepts (if any)
+pub struct Dfa {
+    priv alphabet: ~[char],
+    priv trans: ~[~[Option<StateId>]],
+    priv accept: ~[Option<uint>],
+    priv start: StateId
+}
+
+impl Dfa {
+    /// Determine whether `s` is recognized by this DFA
+    pub fn matches(&self, s: &str) -> bool {
+        self.run(s).is_some()
+    }
+
+    /// Run the DFA over all of `s`, returning the rule index accepted
+    /// in the final state, if any
+    pub fn run(&self, s: &str) -> Option<uint> {
+        let mut cur = self.start;
+        for c in s.chars() {
+            match self.trans[cur][alphabet_index(self.alphabet, c)] {
+                Some(next) => cur = next,
+                None => return None
+            }
+        }
+        self.accept[cur]
+    }
+
+    pub fn state_count(&self) -> uint { self.trans.len() }
+    pub fn alphabet_chars<'r>(&'r self) -> &'r [char] { self.alphabet }
+    pub fn trans_table<'r>(&'r self) -> &'r [~[Option<StateId>]] { self.trans }
+    pub fn accept_table<'r>(&'r self) -> &'r [Option<uint>] { self.accept }
+    pub fn start_state(&self) -> StateId { self.start }
+
+    fn from_nfa(nfa: &Nfa) -> Dfa {
+        let alphabet = collect_alphabet(nfa);
+        let start_set = nfa.eps_closure(&singleton(nfa.start));
+
+        let mut state_sets: ~[HashSet<StateId>] = ~[start_set.clone()];
+        let mut trans: ~[~[Option<StateId>]] = ~[std::vec::from_elem(alphabet.len(), None)];
+        let mut accept: ~[Option<uint>] = ~[nfa.rule_tag(&start_set)];
+        let mut seen: HashMap<~[StateId], StateId> = HashMap::new();
+        seen.insert(sorted_ids(&start_set), 0);
+
+        let mut worklist = ~[0u];
+        loop {
+            match worklist.pop() {
+                None => break,
+                Some(cur) => {
+                    let cur_set = state_sets[cur].clone();
+                    for (i, &rep) in alphabet.iter().enumerate() {
+                        let moved = nfa.step(&cur_set, rep);
+                        if !moved.is_empty() {
+                            let closed = nfa.eps_closure(&moved);
+                            let key = sorted_ids(&closed);
+                            let next = match seen.find(&key) {
+                                Some(&id) => id,
+                                None => {
+                                    let id = state_sets.len();
+                                    state_sets.push(closed.clone());
+                                    accept.push(nfa.rule_tag(&closed));
+                                    trans.push(std::vec::from_elem(alphabet.len(), None));
+                                    seen.insert(key, id);
+                                    worklist.push(id);
+                                    id
+                                }
+                            };
+                            trans[cur][i] = Some(next);
+                        }
+                    }
+                }
+            }
+        }
+
+        Dfa { alphabet: alphabet, trans: trans, accept: accept, start: 0 }
+    }
+
+    /// Collapse equivalent states via Hopcroft-style partition
+    /// refinement, producing an equivalent DFA with as few states as
+    /// possible
+    pub fn minimize(self) -> Dfa {
+        let reachable = self.reachable_states();
+        let mut remap: HashMap<StateId, StateId> = HashMap::new();
+        for (new_id, &old_id) in reachable.iter().enumerate() {
+            remap.insert(old_id, new_id);
+        }
+
+        let accept: ~[Option<uint>] = reachable.iter().map(|&s| self.accept[s]).collect();
+        let trans: ~[~[Option<StateId>]] = reachable.iter().map(|&s| {
+            self.trans[s].iter().map(|&t| {
+                t.and_then(|old| remap.find(&old).map(|&id| id))
+            }).collect()
+        }).collect();
+        let start = *remap.find(&self.start).unwrap();
+
+        let partition = refine_partition(reachable.len(), &trans, &accept, self.alphabet.len());
+
+        let mut block_of: ~[uint] = std::vec::from_elem(reachable.len(), 0u);
+        for (bi, block) in partition.iter().enumerate() {
+            for &s in block.iter() { block_of[s] = bi; }
+        }
+
+        let reps: ~[StateId] = partition.iter().map(|block| *block.iter().next().unwrap()).collect();
+        let m_accept: ~[Option<uint>] = reps.iter().map(|&r| accept[r]).collect();
+        let m_trans: ~[~[Option<StateId>]] = reps.iter().map(|&r| {
+            trans[r].iter().map(|&t| t.map(|to| block_of[to])).collect()
+        }).collect();
+
+        Dfa { alphabet: self.alphabet, trans: m_trans, accept: m_accept, start: block_of[start] }
+    }
+
+    // States reachable from `start`, in ascending order
+    fn reachable_states(&self) -> ~[StateId] {
+        let mut seen = HashSet::new();
+        seen.insert(self.start);
+        let mut stack = ~[self.start];
+        loop {
+            match stack.pop() {
+                None => break,
+                Some(s) => {
+                    for &t in self.trans[s].iter() {
+                        match t {
+                            Some(to) if !seen.contains(&to) => {
+                                seen.insert(to);
+                                stack.push(to);
+                            }
+                            _ => ()
+                        }
+                    }
+                }
+            }
+        }
+        let mut order: ~[StateId] = seen.iter().map(|&s| s).collect();
+        order.sort();
+        order
+    }
+}
+
+// Partition `n` states into blocks of mutually indistinguishable
+// states, starting from one block per distinct accept tag (so states
+// accepting different rules are never merged) and repeatedly splitting
+// a block whenever some other block's states disagree on whether they
+// transition into it on a given symbol
+fn refine_partition(n: uint, trans: &~[~[Option<StateId>]], accept: &~[Option<uint>], alpha_len: uint)
+                     -> ~[HashSet<StateId>] {
+    let mut by_tag: HashMap<Option<uint>, HashSet<StateId>> = HashMap::new();
+    for s in range(0, n) {
+        by_tag.find_or_insert_with(accept[s], |_| HashSet::new()).insert(s);
+    }
+
+    let mut partition: ~[HashSet<StateId>] = by_tag.move_iter().map(|(_, block)| block).collect();
+
+    let mut worklist: ~[(HashSet<StateId>, uint)] = ~[];
+    for block in partition.iter() {
+        for sym in range(0, alpha_len) {
+            worklist.push((block.clone(), sym));
+        }
+    }
+
+    loop {
+        match worklist.pop() {
+            None => break,
+            Some((splitter, sym)) => {
+                let x: HashSet<StateId> = range(0, n).filter(|&s| {
+                    match trans[s][sym] {
+                        Some(t) => splitter.contains(&t),
+                        None => false
+                    }
+                }).collect();
+                if x.is_empty() { continue }
+
+                let mut new_partition: ~[HashSet<StateId>] = ~[];
+                for block in partition.iter() {
+                    let in_x: HashSet<StateId> =
+                        block.iter().filter(|s| x.contains(*s)).map(|&s| s).collect();
+                    let out_x: HashSet<StateId> =
+                        block.iter().filter(|s| !x.contains(*s)).map(|&s| s).collect();
+                    if in_x.is_empty() || out_x.is_empty() {
+                        new_partition.push(block.clone());
+                    } else {
+                        let smaller = if in_x.len() <= out_x.len() { in_x.clone() } else { out_x.clone() };
+                        new_partition.push(in_x);
+                        new_partition.push(out_x);
+                        for s2 in range(0, alpha_len) {
+                            worklist.push((smaller.clone(), s2));
+                        }
+                    }
+                }
+                partition = new_partition;
+            }
+        }
+    }
+
+    partition
+}
+
+fn singleton(s: StateId) -> HashSet<StateId> {
+    let mut set = HashSet::new();
+    set.insert(s);
+    set
+}
+
+fn sorted_ids(states: &HashSet<StateId>) -> ~[StateId] {
+    let mut ids: ~[StateId] = states.iter().map(|&s| s).collect();
+    ids.sort();
+    ids
+}
+
+// The representative characters that partition the input alphabet into
+// equivalence classes for this NFA: one per distinct literal char and
+// one per range boundary. `alphabet_index` maps any char to the
+// partition that contains it.
+fn collect_alphabet(nfa: &Nfa) -> ~[char] {
+    let mut points: ~[char] = ~['\x00'];
+    for &(_, ref lbl, _) in nfa.trans.iter() {
+        match *lbl {
+            Sym(c) => push_char_and_successor(&mut points, c),
+            // `build_fragment` always resolves `Named` items before
+            // storing a class in a `Cls` label, so only `Singles` and
+            // `Range` are ever seen here
+            Cls(ref items, _) => {
+                for item in items.iter() {
+                    match *item {
+                        Singles(ref s) => for c in s.chars() { push_char_and_successor(&mut points, c) },
+                        Range(lo, hi) => {
+                            points.push(lo);
+                            match std::char::from_u32((hi as u32) + 1) {
+                                Some(c) => points.push(c),
+                                None => ()
+                            }
+                        }
+                        Named(ref name) => fail!("Unresolved named class in compiled NFA: {}", *name)
+                    }
+                }
+            }
+            Eps => ()
+        }
+    }
+    points.sort();
+    points.dedup();
+    points
+}
+
+// A literal char `c` only occupies its own partition if the char right
+// after it is also a breakpoint; otherwise every char up to the next
+// breakpoint would be folded into `c`'s partition and wrongly match it
+fn push_char_and_successor(points: &mut ~[char], c: char) {
+    points.push(c);
+    match std::char::from_u32((c as u32) + 1) {
+        Some(next) => points.push(next),
+        None => ()
+    }
+}
+
+// The index of the partition (as produced by `collect_alphabet`) that
+// contains `c`: the rightmost breakpoint that is <= c
+fn alphabet_index(alphabet: &[char], c: char) -> uint {
+    let mut lo = 0u;
+    let mut hi = alphabet.len();
+    while lo + 1 < hi {
+        let mid = (lo + hi) / 2;
+        if alphabet[mid] <= c { lo = mid } else { hi = mid }
+    }
+    lo
+}
+
+/// Compile `ast` into a `Dfa`, resolving named `Symb` references
+/// against `defs`. Fails if `ast` references an undefined symbol or an
+/// unrecognized `[:name:]` class.
+pub fn compile(ast: &Ast, defs: &HashMap<~str, Ast>) -> Result<Dfa, CompileError> {
+    let nfa = try!(Nfa::build(&[ast.clone()], defs));
+    Ok(Dfa::from_nfa(&nfa))
+}
+
+/// Compile several rules into a single `Dfa` that recognizes their
+/// union, each accepting state tagged with the index (into `rules`) of
+/// the rule it matches. Earlier rules take priority over later ones
+/// when an input is accepted by more than one.
+pub fn compile_rules(rules: &[Ast], defs: &HashMap<~str, Ast>) -> Result<Dfa, CompileError> {
+    let nfa = try!(Nfa::build(rules, defs));
+    Ok(Dfa::from_nfa(&nfa))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, compile_rules};
+    use std::hashmap::HashMap;
+    use regexp::{Str, Conc, Union, Star, OnePlus, Opt, Repeat, CharClass, NegClass, Range, Singles, Named};
+
+    #[test]
+    fn test_matches_literal() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let dfa = compile(&Str(~"abc"), &defs).unwrap();
+        assert!(dfa.matches("abc"));
+        assert!(!dfa.matches("ab"));
+        assert!(!dfa.matches("abcd"));
+    }
+
+    #[test]
+    fn test_matches_literal_does_not_absorb_next_char() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let dfa = compile(&Str(~"if"), &defs).unwrap();
+        assert!(dfa.matches("if"));
+        assert!(!dfa.matches("jg"));
+        assert!(!dfa.matches("jf"));
+        assert!(!dfa.matches("ig"));
+    }
+
+    #[test]
+    fn test_matches_union() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let ast = Union(~Str(~"cat"), ~Str(~"dog"));
+        let dfa = compile(&ast, &defs).unwrap();
+        assert!(dfa.matches("cat"));
+        assert!(dfa.matches("dog"));
+        assert!(!dfa.matches("cow"));
+    }
+
+    #[test]
+    fn test_matches_star_and_class() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let digit = CharClass(~[Range('0', '9')]);
+        let ast = OnePlus(~digit);
+        let dfa = compile(&ast, &defs).unwrap();
+        assert!(dfa.matches("0"));
+        assert!(dfa.matches("1234567890"));
+        assert!(!dfa.matches(""));
+        assert!(!dfa.matches("12a"));
+
+        let ast2 = Conc(~Star(~CharClass(~[Singles(~"ab")])), ~Str(~"c"));
+        let dfa2 = compile(&ast2, &defs).unwrap();
+        assert!(dfa2.matches("c"));
+        assert!(dfa2.matches("aababc"));
+        assert!(!dfa2.matches("aabab"));
+    }
+
+    #[test]
+    fn test_symb_reference() {
+        let mut defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        defs.insert(~"digit", CharClass(~[Range('0', '9')]));
+        let ast = ::regexp::Symb(~"digit");
+        let dfa = compile(&ast, &defs).unwrap();
+        assert!(dfa.matches("7"));
+        assert!(!dfa.matches("a"));
+    }
+
+    #[test]
+    fn test_minimize_preserves_matches() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let ast = Conc(~Union(~Str(~"a"), ~Str(~"b")), ~Star(~Str(~"c")));
+        let dfa = compile(&ast, &defs).unwrap().minimize();
+        assert!(dfa.matches("a"));
+        assert!(dfa.matches("b"));
+        assert!(dfa.matches("accc"));
+        assert!(dfa.matches("bc"));
+        assert!(!dfa.matches(""));
+        assert!(!dfa.matches("ac d"));
+    }
+
+    #[test]
+    fn test_minimize_shrinks_state_count() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        // (a|b)*abb has well-known redundant states in its subset-construction DFA
+        let ast = Conc(~Star(~Union(~Str(~"a"), ~Str(~"b"))), ~Str(~"abb"));
+        let unmin = compile(&ast, &defs).unwrap();
+        let unmin_states = unmin.state_count();
+        let min = unmin.minimize();
+        assert!(min.state_count() < unmin_states);
+        assert!(min.matches("abb"));
+        assert!(min.matches("aababb"));
+        assert!(!min.matches("ab"));
+    }
+
+    #[test]
+    fn test_matches_optional() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let ast = Conc(~Str(~"colo"), ~Opt(~Str(~"u")));
+        let ast = Conc(~ast, ~Str(~"r"));
+        let dfa = compile(&ast, &defs).unwrap();
+        assert!(dfa.matches("color"));
+        assert!(dfa.matches("colour"));
+        assert!(!dfa.matches("colouur"));
+    }
+
+    #[test]
+    fn test_matches_bounded_repeat() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let ast = Repeat(~CharClass(~[Range('0', '9')]), 2, Some(3));
+        let dfa = compile(&ast, &defs).unwrap();
+        assert!(!dfa.matches("1"));
+        assert!(dfa.matches("12"));
+        assert!(dfa.matches("123"));
+        assert!(!dfa.matches("1234"));
+
+        let unbounded = Repeat(~Str(~"ab"), 1, None);
+        let dfa2 = compile(&unbounded, &defs).unwrap();
+        assert!(!dfa2.matches(""));
+        assert!(dfa2.matches("ab"));
+        assert!(dfa2.matches("ababab"));
+    }
+
+    #[test]
+    fn test_matches_negated_class() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let ast = NegClass(~[Range('0', '9')]);
+        let dfa = compile(&ast, &defs).unwrap();
+        assert!(dfa.matches("a"));
+        assert!(dfa.matches(" "));
+        assert!(!dfa.matches("5"));
+        assert!(!dfa.matches(""));
+    }
+
+    #[test]
+    fn test_matches_named_class() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let ast = OnePlus(~CharClass(~[Named(~"digit")]));
+        let dfa = compile(&ast, &defs).unwrap();
+        assert!(dfa.matches("0"));
+        assert!(dfa.matches("1920"));
+        assert!(!dfa.matches("19a"));
+
+        let neg = NegClass(~[Named(~"space")]);
+        let dfa2 = compile(&neg, &defs).unwrap();
+        assert!(!dfa2.matches(" "));
+        assert!(dfa2.matches("x"));
+    }
+
+    #[test]
+    fn test_compile_rules_tags_earliest_rule() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let rules = [Str(~"if"), OnePlus(~CharClass(~[Range('a', 'z')]))];
+        let dfa = compile_rules(rules.as_slice(), &defs).unwrap().minimize();
+        // "if" also matches the identifier rule; the earlier rule wins
+        assert_eq!(dfa.run("if"), Some(0));
+        assert_eq!(dfa.run("then"), Some(1));
+        assert_eq!(dfa.run("0x"), None);
+    }
+
+    #[test]
+    fn test_compile_reports_undefined_symbol() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let ast = ::regexp::Symb(~"nope");
+        match compile(&ast, &defs) {
+            Err(super::UndefinedSymbol(ref name)) => assert_eq!(*name, ~"nope"),
+            _ => fail!("expected UndefinedSymbol")
+        }
+    }
+
+    #[test]
+    fn test_compile_reports_unknown_named_class() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let ast = CharClass(~[Named(~"frobnicate")]);
+        match compile(&ast, &defs) {
+            Err(super::UnknownNamedClass(ref name)) => assert_eq!(*name, ~"frobnicate"),
+            _ => fail!("expected UnknownNamedClass")
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//
+// rslex - a lexer generator for rust
+//
+// buffer.rs
+// Lookahead character buffer over source input, with position tracking
+//
+// Andrei de A. Formiga, 2013-08-09
+//
+
+extern mod std;
+
+/// A line/column position in the source text being lexed, used to tag
+/// lexer and parser errors with the location where they occurred
+#[deriving(Eq, Clone)]
+pub struct Position {
+    line: uint,
+    col: uint
+}
+
+impl Position {
+    pub fn new() -> Position {
+        Position { line: 1, col: 1 }
+    }
+}
+
+/// A single character lookahead buffer over a string, tracking the
+/// current line/column as characters are consumed
+pub struct LookaheadBuffer<'r> {
+    priv chars: ~[char],
+    priv idx: uint,
+    priv pushback: Option<char>,
+    priv position: Position,
+    // The position just before the most recent `advance`, so a single
+    // `return_char` can restore it exactly (including across a '\n',
+    // which `advance` can't otherwise be undone for by looking at the
+    // character alone)
+    priv prev_position: Position
+}
+
+impl<'r> LookaheadBuffer<'r> {
+    pub fn new(s: &'r str) -> LookaheadBuffer<'r> {
+        LookaheadBuffer { chars: s.chars().collect(), idx: 0, pushback: None,
+                          position: Position::new(), prev_position: Position::new() }
+    }
+
+    /// The position of the next character to be read
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    pub fn next_char(&mut self) -> Option<char> {
+        let res = match self.pushback {
+            Some(c) => { self.pushback = None; Some(c) }
+            None => {
+                if self.idx < self.chars.len() {
+                    let c = self.chars[self.idx];
+                    self.idx += 1;
+                    Some(c)
+                } else {
+                    None
+                }
+            }
+        };
+        match res {
+            Some(c) => self.advance(c),
+            None => ()
+        }
+        res
+    }
+
+    /// Push a character back onto the buffer so it is returned by the
+    /// next call to `next_char`. Only a single character of pushback
+    /// is supported.
+    pub fn return_char(&mut self, c: char) {
+        self.pushback = Some(c);
+        self.position = self.prev_position;
+    }
+
+    pub fn skip_whitespace(&mut self) {
+        loop {
+            match self.next_char() {
+                Some(c) if std::char::is_whitespace(c) => (),
+                Some(c) => { self.return_char(c); break }
+                None => break
+            }
+        }
+    }
+
+    fn advance(&mut self, c: char) {
+        self.prev_position = self.position;
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.col = 1;
+        } else {
+            self.position.col += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LookaheadBuffer;
+
+    #[test]
+    fn test_return_char_restores_position_across_newline() {
+        let mut b = LookaheadBuffer::new("ab\ncd");
+        b.next_char(); // 'a', now at line 1 col 2
+        b.next_char(); // 'b', now at line 1 col 3
+        assert_eq!(b.next_char(), Some('\n')); // now at line 2 col 1
+        b.return_char('\n');
+        assert_eq!(b.position().line, 1);
+        assert_eq!(b.position().col, 3);
+        assert_eq!(b.next_char(), Some('\n')); // re-read, back to line 2 col 1
+        assert_eq!(b.position().line, 2);
+        assert_eq!(b.position().col, 1);
+    }
+}
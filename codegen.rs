@@ -0,0 +1,235 @@
+//
+// rslex - a lexer generator for rust
+//
+// codegen.rs
+// Emit a standalone, table-driven Rust lexer source file from a
+// compiled, minimized DFA
+//
+// Andrei de A. Formiga, 2013-08-09
+//
+
+use automata::Dfa;
+
+/// Render `dfa` (as produced by `automata::compile_rules`, with rule
+/// `i` tagged with the token name `tokens[i]`) as the source of a
+/// self-contained `.rs` file implementing a maximal-munch scanner.
+/// Saving the result to `lexer.rs` and compiling it alongside the rest
+/// of a project gives a working `next_token` with no further
+/// dependency on rslex itself.
+pub fn generate(dfa: &Dfa, tokens: &[~str]) -> ~str {
+    let mut out = ~"";
+    out.push_str("// Generated by rslex. Do not edit by hand.\n\n");
+    out.push_str(format!("static N_STATES: uint = {};\n", dfa.state_count()));
+    out.push_str(format!("static START_STATE: uint = {};\n", dfa.start_state()));
+    out.push_str(format!("static ALPHABET: &'static [char] = &{};\n\n", fmt_char_slice(dfa.alphabet_chars())));
+    out.push_str(emit_trans_table(dfa).as_slice());
+    out.push_str("\n");
+    out.push_str(emit_accept_table(dfa, tokens).as_slice());
+    out.push_str("\n");
+    out.push_str(NEXT_TOKEN_SRC);
+    out
+}
+
+fn fmt_char_slice(chars: &[char]) -> ~str {
+    let items: ~[~str] = chars.iter().map(|&c| fmt_char_lit(c)).collect();
+    ~"[" + items.connect(", ") + "]"
+}
+
+fn fmt_char_lit(c: char) -> ~str {
+    match c {
+        '\n' => ~"'\\n'",
+        '\t' => ~"'\\t'",
+        '\r' => ~"'\\r'",
+        '\'' => ~"'\\''",
+        '\\' => ~"'\\\\'",
+        // Any other control character (and the DEL char) can't be
+        // written literally inside a `'...'` char literal; escape it
+        // the same way the lexer's own `\xHH` escapes do
+        c if (c as u32) < 0x20 || c == '\x7f' => format!("'\\x{}'", fmt_hex_byte(c as u32)),
+        c => format!("'{}'", c)
+    }
+}
+
+fn fmt_hex_byte(n: u32) -> ~str {
+    let digits = "0123456789abcdef";
+    let hi = ((n >> 4) & 0xf) as uint;
+    let lo = (n & 0xf) as uint;
+    format!("{}{}", digits.char_at(hi), digits.char_at(lo))
+}
+
+// The number of bytes `c` occupies when encoded as UTF-8; mirrors the
+// `utf8_char_len` embedded in `NEXT_TOKEN_SRC`, which can't call back
+// into rslex itself since the generated lexer must stand alone
+fn utf8_char_len(c: char) -> uint {
+    let cp = c as u32;
+    if cp < 0x80 { 1 }
+    else if cp < 0x800 { 2 }
+    else if cp < 0x10000 { 3 }
+    else { 4 }
+}
+
+// The index into `ALPHABET` of the partition containing `c`: the
+// rightmost breakpoint that is <= c
+fn fmt_alphabet_index() -> ~str {
+    ~"fn alphabet_index(c: char) -> uint {
+    let mut lo = 0u;
+    let mut hi = ALPHABET.len();
+    while lo + 1 < hi {
+        let mid = (lo + hi) / 2;
+        if ALPHABET[mid] <= c { lo = mid } else { hi = mid }
+    }
+    lo
+}\n"
+}
+
+// A static `TRANS[state][alphabet_index]` table of `Option<uint>` next
+// states
+fn emit_trans_table(dfa: &Dfa) -> ~str {
+    let mut out = ~"";
+    out.push_str(format!("static TRANS: [[Option<uint>, ..{}], ..{}] = [\n",
+                          dfa.alphabet_chars().len(), dfa.state_count()));
+    for row in dfa.trans_table().iter() {
+        let cells: ~[~str] = row.iter().map(|&t| match t {
+            Some(s) => format!("Some({})", s),
+            None => ~"None"
+        }).collect();
+        out.push_str(format!("    [{}],\n", cells.connect(", ")));
+    }
+    out.push_str("];\n\n");
+    out.push_str(fmt_alphabet_index().as_slice());
+    out
+}
+
+// A static `ACCEPT[state]` table mapping each state to the token it
+// accepts, if any
+fn emit_accept_table(dfa: &Dfa, tokens: &[~str]) -> ~str {
+    let mut out = ~"";
+    out.push_str("#[deriving(Eq, Clone)]\n");
+    out.push_str("pub enum Token {\n");
+    for name in tokens.iter() {
+        out.push_str(format!("    {},\n", token_variant(*name)));
+    }
+    out.push_str("}\n\n");
+
+    let cells: ~[~str] = dfa.accept_table().iter().map(|&t| match t {
+        Some(rule) => format!("Some(Token::{})", token_variant(tokens[rule])),
+        None => ~"None"
+    }).collect();
+    out.push_str(format!("static ACCEPT: [Option<Token>, ..{}] = [{}];\n",
+                          dfa.state_count(), cells.connect(", ")));
+    out
+}
+
+// A token name is used unchanged as its `Token` enum variant
+// identifier; it is not checked to be a valid Rust identifier
+fn token_variant(name: &str) -> ~str {
+    name.to_owned()
+}
+
+static NEXT_TOKEN_SRC: &'static str =
+"
+// Scan the longest prefix of `input[pos..]` that any rule accepts
+// (maximal munch), breaking ties between rules by declaration order,
+// and return the recognized token together with the position just
+// past it. Returns `None` if no rule matches a non-empty prefix.
+pub fn next_token(input: &str, pos: uint) -> Option<(Token, uint)> {
+    let mut state = START_STATE;
+    let mut last_accept: Option<(Token, uint)> = None;
+    let mut cur = pos;
+    for c in input.slice_from(pos).chars() {
+        let next = TRANS[state][alphabet_index(c)];
+        match next {
+            Some(s) => {
+                state = s;
+                // `cur` is a byte offset into `input`, but `c` may be a
+                // multi-byte UTF-8 char, so advance by its encoded width
+                cur += utf8_char_len(c);
+                match ACCEPT[state] {
+                    Some(ref tok) => last_accept = Some((tok.clone(), cur)),
+                    None => ()
+                }
+            }
+            None => break
+        }
+    }
+    last_accept
+}
+
+// The number of bytes `c` occupies when encoded as UTF-8
+fn utf8_char_len(c: char) -> uint {
+    let cp = c as u32;
+    if cp < 0x80 { 1 }
+    else if cp < 0x800 { 2 }
+    else if cp < 0x10000 { 3 }
+    else { 4 }
+}
+";
+
+#[cfg(test)]
+mod tests {
+    use super::{generate, fmt_char_lit, utf8_char_len};
+    use automata::compile_rules;
+    use std::char;
+    use std::hashmap::HashMap;
+    use regexp::{Str, OnePlus, CharClass, Range, Named, NegClass};
+
+    #[test]
+    fn test_generate_contains_tables_and_scanner() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let rules = [Str(~"if"), OnePlus(~CharClass(~[Range('a', 'z')]))];
+        let dfa = compile_rules(rules.as_slice(), &defs).unwrap().minimize();
+        let src = generate(&dfa, [~"If", ~"Ident"]);
+
+        assert!(src.contains("static TRANS:"));
+        assert!(src.contains("static ACCEPT:"));
+        assert!(src.contains("pub enum Token {"));
+        assert!(src.contains("If,"));
+        assert!(src.contains("Ident,"));
+        assert!(src.contains("pub fn next_token"));
+    }
+
+    #[test]
+    fn test_fmt_char_lit_escapes_control_chars() {
+        assert_eq!(fmt_char_lit('a'), ~"'a'");
+        assert_eq!(fmt_char_lit('\n'), ~"'\\n'");
+        assert_eq!(fmt_char_lit('\x00'), ~"'\\x00'");
+        assert_eq!(fmt_char_lit('\x0b'), ~"'\\x0b'");
+        assert_eq!(fmt_char_lit('\x0c'), ~"'\\x0c'");
+        assert_eq!(fmt_char_lit('\x7f'), ~"'\\x7f'");
+    }
+
+    #[test]
+    fn test_generate_escapes_named_class_control_chars() {
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let rules = [NegClass(~[Named(~"space")])];
+        let dfa = compile_rules(rules.as_slice(), &defs).unwrap().minimize();
+        let src = generate(&dfa, [~"NonSpace"]);
+        assert!(!src.contains("'\x0b'"));
+        assert!(src.contains("'\\x0b'"));
+    }
+
+    #[test]
+    fn test_utf8_char_len() {
+        assert_eq!(utf8_char_len('a'), 1u);
+        assert_eq!(utf8_char_len('\x7f'), 1u);
+        assert_eq!(utf8_char_len('é'), 2u);
+        assert_eq!(utf8_char_len('€'), 3u);
+        assert_eq!(utf8_char_len(char::from_u32(0x1f600).unwrap()), 4u);
+    }
+
+    #[test]
+    fn test_generate_advances_cur_by_utf8_byte_length() {
+        // `cur` is a byte offset fed back into `input.slice_from(pos)`,
+        // so advancing it by a flat 1 per `char` (rather than by the
+        // char's encoded UTF-8 width) would panic or resume mid-char
+        // the first time a rule matches a non-ASCII character
+        let defs: HashMap<~str, ::regexp::Ast> = HashMap::new();
+        let rules = [OnePlus(~CharClass(~[Range('a', 'z')]))];
+        let dfa = compile_rules(rules.as_slice(), &defs).unwrap().minimize();
+        let src = generate(&dfa, [~"Ident"]);
+
+        assert!(!src.contains("cur += 1;"));
+        assert!(src.contains("cur += utf8_char_len(c);"));
+        assert!(src.contains("fn utf8_char_len(c: char) -> uint"));
+    }
+}
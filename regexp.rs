@@ -9,19 +9,39 @@
 
 extern mod std;
 
-use buffer::LookaheadBuffer;
+use buffer::{LookaheadBuffer, Position};
 
 #[deriving(Eq, Clone)]
-enum Token { LBrack, RBrack, Id(~str), LParen, RParen, Asterisk, 
-             Plus, Bar, Dash, String(~str), End, Eof, Error(char) }
+enum Token { LBrack, RBrack, Id(~str), LParen, RParen, Asterisk,
+             Plus, Question, LBrace, RBrace, Comma, Num(uint),
+             Bar, Dash, Caret, Colon, String(~str), End, Eof }
 
-#[deriving(Eq)]
-pub enum ClassItem { Singles(~str), Range(char, char) }
+#[deriving(Eq, Clone)]
+pub enum ClassItem { Singles(~str), Range(char, char), Named(~str) }
 
-#[deriving(Eq)]
+#[deriving(Eq, Clone)]
 pub enum Ast { Symb(~str), Str(~str), Union(~Ast, ~Ast),
-               Conc(~Ast, ~Ast), Star(~Ast), OnePlus(~Ast), 
-               CharClass(~[ClassItem]), Epsilon }
+               Conc(~Ast, ~Ast), Star(~Ast), OnePlus(~Ast), Opt(~Ast),
+               Repeat(~Ast, uint, Option<uint>),
+               CharClass(~[ClassItem]), NegClass(~[ClassItem]), Epsilon }
+
+/// Errors raised while turning raw input into tokens
+#[deriving(Eq, Clone)]
+pub enum LexError {
+    UnexpectedChar(char, Position),
+    UnclosedString(Position),
+    MalformedEscapeSequence(Position)
+}
+
+/// Errors raised while parsing a token stream into an `Ast`
+#[deriving(Eq, Clone)]
+pub enum ParseError {
+    Lex(LexError),
+    MalformedClassRange(Position),
+    MalformedCharRange(Position),
+    MalformedRepeatBound(Position),
+    UnexpectedToken(Position)
+}
 
 /// A token stream with capacity for lookahead of 1 token
 struct TokenStream<'r> {
@@ -35,32 +55,38 @@ impl<'r> TokenStream<'r> {
         TokenStream { buffer: buffer, term: term, peek: None }
     }
 
-    fn next_token(&mut self) -> Token {
-        let res = match self.peek {
-            None => self.next_token_raw(),
-            Some(ref t) => (*t).clone()
-        };
-        self.peek = None;
-        res
+    fn next_token(&mut self) -> Result<Token, LexError> {
+        match self.peek.take() {
+            Some(t) => Ok(t),
+            None => self.next_token_raw()
+        }
     }
 
-    fn next_token_raw(&mut self) -> Token {
+    fn next_token_raw(&mut self) -> Result<Token, LexError> {
         self.buffer.skip_whitespace();
+        let pos = self.buffer.position();
         match self.buffer.next_char() {
-            Some('[') => LBrack,
-            Some(']') => RBrack,
-            Some('(') => LParen,
-            Some(')') => RParen,
-            Some('*') => Asterisk,
-            Some('+') => Plus,
-            Some('|') => Bar,
-            Some('-') => Dash,
-            Some('\'') => String(self.parse_string('\'')),
-            Some('"') => String(self.parse_string('"')),
-            Some(c) if std::char::is_alphabetic(c) => Id(self.parse_id(c)),
-            Some(c) if is_terminator(c, self.term) => End,
-            None => Eof,
-            Some(c) => Error(c)
+            Some(c) if is_terminator(c, self.term) => Ok(End),
+            Some('[') => Ok(LBrack),
+            Some(']') => Ok(RBrack),
+            Some('(') => Ok(LParen),
+            Some(')') => Ok(RParen),
+            Some('*') => Ok(Asterisk),
+            Some('+') => Ok(Plus),
+            Some('?') => Ok(Question),
+            Some('{') => Ok(LBrace),
+            Some('}') => Ok(RBrace),
+            Some(',') => Ok(Comma),
+            Some('|') => Ok(Bar),
+            Some('-') => Ok(Dash),
+            Some('^') => Ok(Caret),
+            Some(':') => Ok(Colon),
+            Some('\'') => self.parse_string('\'').map(|s| String(s)),
+            Some('"') => self.parse_string('"').map(|s| String(s)),
+            Some(c) if std::char::is_digit(c) => Ok(Num(self.parse_num(c))),
+            Some(c) if std::char::is_alphabetic(c) => Ok(Id(self.parse_id(c))),
+            None => Ok(Eof),
+            Some(c) => Err(UnexpectedChar(c, pos))
         }
     }
 
@@ -68,16 +94,72 @@ impl<'r> TokenStream<'r> {
         self.peek = Some(tok);
     }
 
-    fn parse_string(&mut self, delim: char) -> ~str {
+    fn parse_string(&mut self, delim: char) -> Result<~str, LexError> {
+        let start = self.buffer.position();
         let mut res : ~str = ~"";
         loop {
             match self.buffer.next_char() {
-                None => fail!("Unexpected end of file. Expected closing {}", delim),
+                None => return Err(UnclosedString(start)),
                 Some(c) if c == delim => break,
+                Some('\\') => res.push_char(try!(self.parse_escape())),
                 Some(c) => res.push_char(c)
             }
         }
-        res    
+        Ok(res)
+    }
+
+    /// Parse the character(s) following a `\` inside a quoted literal,
+    /// returning the single char they denote
+    fn parse_escape(&mut self) -> Result<char, LexError> {
+        let pos = self.buffer.position();
+        match self.buffer.next_char() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some('\\') => Ok('\\'),
+            Some('\'') => Ok('\''),
+            Some('"') => Ok('"'),
+            Some('x') => self.parse_hex_escape(2, pos),
+            Some('u') => self.parse_unicode_escape(pos),
+            _ => Err(MalformedEscapeSequence(pos))
+        }
+    }
+
+    fn parse_hex_escape(&mut self, ndigits: uint, pos: Position) -> Result<char, LexError> {
+        let mut n: u32 = 0;
+        for _ in range(0, ndigits) {
+            match self.buffer.next_char().and_then(|c| std::char::to_digit(c, 16)) {
+                Some(d) => n = n * 16 + (d as u32),
+                None => return Err(MalformedEscapeSequence(pos))
+            }
+        }
+        match std::char::from_u32(n) {
+            Some(c) => Ok(c),
+            None => Err(MalformedEscapeSequence(pos))
+        }
+    }
+
+    fn parse_unicode_escape(&mut self, pos: Position) -> Result<char, LexError> {
+        match self.buffer.next_char() {
+            Some('{') => (),
+            _ => return Err(MalformedEscapeSequence(pos))
+        }
+        let mut n: u32 = 0;
+        let mut saw_digit = false;
+        loop {
+            match self.buffer.next_char() {
+                Some('}') if saw_digit => break,
+                Some(c) => match std::char::to_digit(c, 16) {
+                    Some(d) => { n = n * 16 + (d as u32); saw_digit = true }
+                    None => return Err(MalformedEscapeSequence(pos))
+                },
+                None => return Err(MalformedEscapeSequence(pos))
+            }
+        }
+        match std::char::from_u32(n) {
+            Some(c) => Ok(c),
+            None => Err(MalformedEscapeSequence(pos))
+        }
     }
 
     fn parse_id(&mut self, first: char) -> ~str {
@@ -92,6 +174,20 @@ impl<'r> TokenStream<'r> {
         }
         res
     }
+
+    fn parse_num(&mut self, first: char) -> uint {
+        let mut res = std::char::to_digit(first, 10).unwrap() as uint;
+        loop {
+            match self.buffer.next_char() {
+                Some(c) if std::char::is_digit(c) => {
+                    res = res * 10 + (std::char::to_digit(c, 10).unwrap() as uint);
+                }
+                Some(c) => { self.buffer.return_char(c); break }
+                None => break
+            }
+        }
+        res
+    }
 }
 
 
@@ -101,10 +197,13 @@ fn is_id_char(c: char) -> bool {
 }
 
 #[inline]
-fn match_next_token(ts: &mut TokenStream, t: Token) {
-    let rt = ts.next_token();
+fn match_next_token(ts: &mut TokenStream, t: Token) -> Result<(), ParseError> {
+    let pos = ts.buffer.position();
+    let rt = try!(ts.next_token().map_err(Lex));
     if rt != t {
-        fail!("Unexpeced token: expected {:?}, got {:?}", t, rt);
+        Err(UnexpectedToken(pos))
+    } else {
+        Ok(())
     }
 }
 
@@ -117,70 +216,119 @@ fn is_terminator(c: char, term: &[char]) -> bool {
 // regexp := union
 // union  := union '|' concat | concat
 // concat := concat factor | factor
-// factor := (regexp) | regexp'*' | regexp'+' | class | id | str
-// class  := '[' (char | range)* ']'
+// factor := (regexp) | regexp'*' | regexp'+' | regexp'?' | regexp rep | class | id | str
+// rep    := '{'num'}' | '{'num','num'}' | '{'num',}'
+// class  := '[' '^'? (char | range | named)* ']'
 // range  := char'-'char
+// named  := ':' id ':'
 
 // parse a regexp from the token stream until one of the terminators in term occurs
-pub fn parse_regexp(ts: &mut TokenStream) -> Ast {
+pub fn parse_regexp(ts: &mut TokenStream) -> Result<Ast, ParseError> {
     parse_union(ts)
 }
 
-fn parse_union(ts: &mut TokenStream) -> Ast {
-    let left = parse_concat(ts);
-    match ts.next_token() {
+fn parse_union(ts: &mut TokenStream) -> Result<Ast, ParseError> {
+    let left = try!(parse_concat(ts));
+    match try!(ts.next_token().map_err(Lex)) {
         Bar => {
-            let right = parse_union(ts);
-            Union(~left, ~right)
+            let right = try!(parse_union(ts));
+            Ok(Union(~left, ~right))
         }
         tok => {
             ts.return_token(tok);
-            left
+            Ok(left)
         }
     }
 }
 
-fn parse_concat(ts: &mut TokenStream) -> Ast {
-    let left = parse_factor(ts);
-    match ts.next_token() {
+fn parse_concat(ts: &mut TokenStream) -> Result<Ast, ParseError> {
+    let left = try!(parse_factor(ts));
+    match try!(ts.next_token().map_err(Lex)) {
         Bar => {
             ts.return_token(Bar);
-            left
+            Ok(left)
         }
         End => {
             ts.return_token(End);
-            left
+            Ok(left)
         }
         RParen => {
             ts.return_token(RParen);
-            left
+            Ok(left)
         }
         tok => {
             ts.return_token(tok);
-            let right = parse_concat(ts);
-            Conc(~left, ~right)
+            let right = try!(parse_concat(ts));
+            Ok(Conc(~left, ~right))
         }
     }
 }
 
-fn trailing_closure(ts: &mut TokenStream) -> Option<Token> {
-    match ts.next_token() {
-        Asterisk => Some(Asterisk),
-        Plus => Some(Plus),
-        t => { ts.return_token(t); None }
+// The kinds of trailing repetition operator a factor may be followed by
+enum RepOp { StarRep, PlusRep, OptRep, BoundedRep(uint, Option<uint>) }
+
+fn trailing_closure(ts: &mut TokenStream) -> Result<Option<RepOp>, ParseError> {
+    match try!(ts.next_token().map_err(Lex)) {
+        Asterisk => Ok(Some(StarRep)),
+        Plus => Ok(Some(PlusRep)),
+        Question => Ok(Some(OptRep)),
+        LBrace => parse_repeat_bound(ts).map(|b| Some(b)),
+        t => { ts.return_token(t); Ok(None) }
+    }
+}
+
+// repeat := '{' num '}' | '{' num ',' '}' | '{' num ',' num '}'
+fn parse_repeat_bound(ts: &mut TokenStream) -> Result<RepOp, ParseError> {
+    let pos = ts.buffer.position();
+    let m = match try!(ts.next_token().map_err(Lex)) {
+        Num(n) => n,
+        _ => return Err(UnexpectedToken(pos))
+    };
+    let n = match try!(ts.next_token().map_err(Lex)) {
+        RBrace => return Ok(BoundedRep(m, Some(m))),
+        End if !is_terminator(',', ts.term) => return Err(UnexpectedToken(pos)),
+        // A ',' that is one of the terminators is lexed as `End`
+        Comma | End => match try!(ts.next_token().map_err(Lex)) {
+            RBrace => None,
+            Num(n) => { try!(match_next_token(ts, RBrace)); Some(n) }
+            _ => return Err(UnexpectedToken(pos))
+        },
+        _ => return Err(UnexpectedToken(pos))
+    };
+    match n {
+        Some(n) if n < m => Err(MalformedRepeatBound(pos)),
+        n => Ok(BoundedRep(m, n))
     }
 }
 
-fn parse_character_class(ts: &mut TokenStream) -> Ast {
+fn parse_character_class(ts: &mut TokenStream) -> Result<Ast, ParseError> {
+    let negated = match try!(ts.next_token().map_err(Lex)) {
+        Caret => true,
+        tok => { ts.return_token(tok); false }
+    };
     let mut res = std::vec::with_capacity(2);
     loop {
-        match ts.next_token() {
+        let pos = ts.buffer.position();
+        match try!(ts.next_token().map_err(Lex)) {
+            Colon => {
+                let name = match try!(ts.next_token().map_err(Lex)) {
+                    Id(name) => name,
+                    _ => return Err(MalformedClassRange(pos))
+                };
+                try!(match_next_token(ts, Colon));
+                res.push(Named(name))
+            }
             String(s1) => {
-                match ts.next_token() {
+                match try!(ts.next_token().map_err(Lex)) {
                     Dash => {
-                        match ts.next_token() {
-                            String(s2) => res.push(Range(s1.char_at(0), s2.char_at(0))),
-                            _ => fail!("Ill-formed character class range")
+                        match try!(ts.next_token().map_err(Lex)) {
+                            String(s2) => {
+                                if s1.char_len() != 1 || s2.char_len() != 1 {
+                                    return Err(MalformedCharRange(pos))
+                                }
+                                res.push(Range(s1.char_at(0), s2.char_at(0)))
+                            }
+                            _ => return Err(MalformedClassRange(pos))
                         }
                     }
                     tok => {
@@ -189,38 +337,44 @@ fn parse_character_class(ts: &mut TokenStream) -> Ast {
                     }
                 }
             }
-            Dash => fail!("Ill-formed character class range"),
+            Dash => return Err(MalformedClassRange(pos)),
             RBrack => break,
-            tok => fail!("Unexpected token in character class: {:?}", tok)
+            _ => return Err(UnexpectedToken(pos))
         }
     }
-    CharClass(res)
+    if negated {
+        Ok(NegClass(res))
+    } else {
+        Ok(CharClass(res))
+    }
 }
 
 #[inline]
-fn parse_factor(ts: &mut TokenStream) -> Ast {
-    let pre = match ts.next_token() {
-        LParen => { let e = parse_regexp(ts); 
-                    match_next_token(ts, RParen); 
+fn parse_factor(ts: &mut TokenStream) -> Result<Ast, ParseError> {
+    let pos = ts.buffer.position();
+    let pre = match try!(ts.next_token().map_err(Lex)) {
+        LParen => { let e = try!(parse_regexp(ts));
+                    try!(match_next_token(ts, RParen));
                     e }
-        LBrack => parse_character_class(ts),
+        LBrack => try!(parse_character_class(ts)),
         Id(s) => Symb(s),
         String(s) => Str(s),
-        tok => fail!("Unexpected token in regexp: {:?}", tok)
+        _ => return Err(UnexpectedToken(pos))
     };
-    match trailing_closure(ts) {
-        Some(Asterisk) => Star(~pre),
-        Some(Plus) => OnePlus(~pre),
-        Some(_) => fail!("Unexpected closure character"),
-        None => pre
+    match try!(trailing_closure(ts)) {
+        Some(StarRep) => Ok(Star(~pre)),
+        Some(PlusRep) => Ok(OnePlus(~pre)),
+        Some(OptRep) => Ok(Opt(~pre)),
+        Some(BoundedRep(m, n)) => Ok(Repeat(~pre, m, n)),
+        None => Ok(pre)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use super::{LBrack, RBrack, Id, LParen, RParen, Asterisk, Bar, Dash, String, End, Eof, Error };
-    use super::{CharClass};
+    use super::{LBrack, RBrack, Id, LParen, RParen, Asterisk, Bar, Dash, String, End, Eof };
+    use super::{CharClass, NegClass};
     use super::{parse_character_class};
     use buffer::LookaheadBuffer;
 
@@ -229,22 +383,41 @@ mod tests {
         let term = [','];
         let mut b1 = LookaheadBuffer::new("abc'* ");
         let mut ts1 = TokenStream::new(&mut b1, term);
-        assert_eq!(ts1.parse_string('\''), ~"abc");
+        assert_eq!(ts1.parse_string('\''), Ok(~"abc"));
         assert_eq!(ts1.buffer.next_char(), Some('*'));
 
         let mut b2 = LookaheadBuffer::new("abc'def\"  ");
         let mut ts2 = TokenStream::new(&mut b2, term);
-        assert_eq!(ts2.parse_string('"'), ~"abc'def");
-        assert_eq!(ts2.buffer.next_char(), Some(' ')); 
+        assert_eq!(ts2.parse_string('"'), Ok(~"abc'def"));
+        assert_eq!(ts2.buffer.next_char(), Some(' '));
     }
 
     #[test]
-    #[should_fail]
     fn unclosed_string_ts() {
         let term = [','];
         let mut b1 = LookaheadBuffer::new("abc'def  ");
         let mut ts1 = TokenStream::new(&mut b1, term);
-        assert_eq!(ts1.parse_string('"'), ~"abc'def");
+        assert!(ts1.parse_string('"').is_err());
+    }
+
+    #[test]
+    fn test_parse_string_escapes() {
+        let term = [','];
+        let mut b1 = LookaheadBuffer::new("a\\tb\\nc'");
+        let mut ts1 = TokenStream::new(&mut b1, term);
+        assert_eq!(ts1.parse_string('\''), Ok(~"a\tb\nc"));
+
+        let mut b2 = LookaheadBuffer::new("\\x41\\x42'");
+        let mut ts2 = TokenStream::new(&mut b2, term);
+        assert_eq!(ts2.parse_string('\''), Ok(~"AB"));
+
+        let mut b3 = LookaheadBuffer::new("\\u{41}\\u{1f600}'");
+        let mut ts3 = TokenStream::new(&mut b3, term);
+        assert_eq!(ts3.parse_string('\''), Ok(~"A\U0001F600"));
+
+        let mut b4 = LookaheadBuffer::new("\\q'");
+        let mut ts4 = TokenStream::new(&mut b4, term);
+        assert!(ts4.parse_string('\'').is_err());
     }
 
     #[test]
@@ -278,56 +451,56 @@ mod tests {
         let term = [','];
         let mut b1 = LookaheadBuffer::new("'return'");
         let mut ts1 = TokenStream::new(&mut b1, term);
-        assert_eq!(ts1.next_token(), String(~"return"));
+        assert_eq!(ts1.next_token(), Ok(String(~"return")));
 
         let mut b2 = LookaheadBuffer::new("return");
         let mut ts2 = TokenStream::new(&mut b2, term);
-        assert_eq!(ts2.next_token(), Id(~"return"));
+        assert_eq!(ts2.next_token(), Ok(Id(~"return")));
         ts2.return_token(Id(~"return"));
-        assert_eq!(ts2.next_token(), Id(~"return"));
-        assert_eq!(ts2.next_token(), Eof);
+        assert_eq!(ts2.next_token(), Ok(Id(~"return")));
+        assert_eq!(ts2.next_token(), Ok(Eof));
 
         let mut b3 = LookaheadBuffer::new("(['a'-'z'])(['A'-'Z'])*");
         let mut ts3 = TokenStream::new(&mut b3, term);
-        assert_eq!(ts3.next_token(), LParen);
-        assert_eq!(ts3.next_token(), LBrack);
-        assert_eq!(ts3.next_token(), String(~"a"));
-        assert_eq!(ts3.next_token(), Dash);
-        assert_eq!(ts3.next_token(), String(~"z"));
-        assert_eq!(ts3.next_token(), RBrack);
-        assert_eq!(ts3.next_token(), RParen);
-        assert_eq!(ts3.next_token(), LParen);
+        assert_eq!(ts3.next_token(), Ok(LParen));
+        assert_eq!(ts3.next_token(), Ok(LBrack));
+        assert_eq!(ts3.next_token(), Ok(String(~"a")));
+        assert_eq!(ts3.next_token(), Ok(Dash));
+        assert_eq!(ts3.next_token(), Ok(String(~"z")));
+        assert_eq!(ts3.next_token(), Ok(RBrack));
+        assert_eq!(ts3.next_token(), Ok(RParen));
+        assert_eq!(ts3.next_token(), Ok(LParen));
 
         assert_eq!(ts3.peek, None);
         ts3.return_token(LParen);
         assert!(!ts3.peek.is_none());
-        assert_eq!(ts3.next_token(), LParen);
+        assert_eq!(ts3.next_token(), Ok(LParen));
         assert_eq!(ts3.peek, None);
 
-        assert_eq!(ts3.next_token(), LBrack);
-        assert_eq!(ts3.next_token(), String(~"A"));
-        assert_eq!(ts3.next_token(), Dash);
-        assert_eq!(ts3.next_token(), String(~"Z"));
-        assert_eq!(ts3.next_token(), RBrack);
-        assert_eq!(ts3.next_token(), RParen);
-        assert_eq!(ts3.next_token(), Asterisk);
-        assert_eq!(ts3.next_token(), Eof);
+        assert_eq!(ts3.next_token(), Ok(LBrack));
+        assert_eq!(ts3.next_token(), Ok(String(~"A")));
+        assert_eq!(ts3.next_token(), Ok(Dash));
+        assert_eq!(ts3.next_token(), Ok(String(~"Z")));
+        assert_eq!(ts3.next_token(), Ok(RBrack));
+        assert_eq!(ts3.next_token(), Ok(RParen));
+        assert_eq!(ts3.next_token(), Ok(Asterisk));
+        assert_eq!(ts3.next_token(), Ok(Eof));
 
         let mut b4 = LookaheadBuffer::new("letter \t (letter | digit)*,");
         let mut ts4 = TokenStream::new(&mut b4, term);
-        assert_eq!(ts4.next_token(), Id(~"letter"));
-        assert_eq!(ts4.next_token(), LParen);
-        assert_eq!(ts4.next_token(), Id(~"letter"));
-        assert_eq!(ts4.next_token(), Bar);
-        assert_eq!(ts4.next_token(), Id(~"digit"));
-        assert_eq!(ts4.next_token(), RParen);
-        assert_eq!(ts4.next_token(), Asterisk);
-        assert_eq!(ts4.next_token(), End);
+        assert_eq!(ts4.next_token(), Ok(Id(~"letter")));
+        assert_eq!(ts4.next_token(), Ok(LParen));
+        assert_eq!(ts4.next_token(), Ok(Id(~"letter")));
+        assert_eq!(ts4.next_token(), Ok(Bar));
+        assert_eq!(ts4.next_token(), Ok(Id(~"digit")));
+        assert_eq!(ts4.next_token(), Ok(RParen));
+        assert_eq!(ts4.next_token(), Ok(Asterisk));
+        assert_eq!(ts4.next_token(), Ok(End));
 
         let mut b5 = LookaheadBuffer::new("let  & dig,");
         let mut ts5 = TokenStream::new(&mut b5, term);
-        assert_eq!(ts5.next_token(), Id(~"let"));
-        assert_eq!(ts5.next_token(), Error('&'));
+        assert_eq!(ts5.next_token(), Ok(Id(~"let")));
+        assert!(ts5.next_token().is_err());
     }
 
     #[test]
@@ -335,40 +508,83 @@ mod tests {
         let term = [','];
         let mut b1 = LookaheadBuffer::new("'A'-'Z'],");
         let mut ts1 = TokenStream::new(&mut b1, term);
-        assert_eq!(parse_character_class(&mut ts1), CharClass(~[Range('A', 'Z')]));
-        assert_eq!(ts1.next_token(), End);
+        assert_eq!(parse_character_class(&mut ts1), Ok(CharClass(~[Range('A', 'Z')])));
+        assert_eq!(ts1.next_token(), Ok(End));
 
         let mut b2 = LookaheadBuffer::new("]");
         let mut ts2 = TokenStream::new(&mut b2, term);
-        assert_eq!(parse_character_class(&mut ts2), CharClass(~[]));
+        assert_eq!(parse_character_class(&mut ts2), Ok(CharClass(~[])));
 
         let mut b3 = LookaheadBuffer::new("'abcABC']");
         let mut ts3 = TokenStream::new(&mut b3, term);
-        assert_eq!(parse_character_class(&mut ts3), CharClass(~[Singles(~"abcABC")]));
+        assert_eq!(parse_character_class(&mut ts3), Ok(CharClass(~[Singles(~"abcABC")])));
 
         let mut b4 = LookaheadBuffer::new("'ab''cd''0'-'9''55']");
         let mut ts4 = TokenStream::new(&mut b4, term);
-        assert_eq!(parse_character_class(&mut ts4), 
-                   CharClass(~[Singles(~"ab"), Singles(~"cd"), 
-                               Range('0', '9'), Singles(~"55")]));
+        assert_eq!(parse_character_class(&mut ts4),
+                   Ok(CharClass(~[Singles(~"ab"), Singles(~"cd"),
+                                  Range('0', '9'), Singles(~"55")])));
     }
 
     #[test]
-    #[should_fail]
     fn test_bad_charclass() {
         let term = [','];
         let mut b1 = LookaheadBuffer::new("'A'--'Z'],");
         let mut ts1 = TokenStream::new(&mut b1, term);
-        assert_eq!(parse_character_class(&mut ts1), CharClass(~[Range('A', 'Z')]));
+        assert!(parse_character_class(&mut ts1).is_err());
     }
 
     #[test]
-    #[should_fail]
     fn test_bad_charclass2() {
         let term = [','];
         let mut b1 = LookaheadBuffer::new("'A'*'Z'],");
         let mut ts1 = TokenStream::new(&mut b1, term);
-        assert_eq!(parse_character_class(&mut ts1), CharClass(~[Range('A', 'Z')]));
+        assert!(parse_character_class(&mut ts1).is_err());
+    }
+
+    #[test]
+    fn test_charclass_multichar_range_rejected() {
+        let term = [','];
+        let mut b1 = LookaheadBuffer::new("'AB'-'Z'],");
+        let mut ts1 = TokenStream::new(&mut b1, term);
+        assert!(parse_character_class(&mut ts1).is_err());
+
+        let mut b2 = LookaheadBuffer::new("'A'-'YZ'],");
+        let mut ts2 = TokenStream::new(&mut b2, term);
+        assert!(parse_character_class(&mut ts2).is_err());
+    }
+
+    #[test]
+    fn test_parse_negated_charclass() {
+        let term = [','];
+        let mut b1 = LookaheadBuffer::new("^'0'-'9']");
+        let mut ts1 = TokenStream::new(&mut b1, term);
+        assert_eq!(parse_character_class(&mut ts1), Ok(NegClass(~[Range('0', '9')])));
+
+        let mut b2 = LookaheadBuffer::new("'a'-'z']");
+        let mut ts2 = TokenStream::new(&mut b2, term);
+        assert_eq!(parse_character_class(&mut ts2), Ok(CharClass(~[Range('a', 'z')])));
+    }
+
+    #[test]
+    fn test_parse_named_charclass() {
+        let term = [','];
+        let mut b1 = LookaheadBuffer::new(":digit:]");
+        let mut ts1 = TokenStream::new(&mut b1, term);
+        assert_eq!(parse_character_class(&mut ts1), Ok(CharClass(~[Named(~"digit")])));
+
+        let mut b2 = LookaheadBuffer::new("^:space:'_']");
+        let mut ts2 = TokenStream::new(&mut b2, term);
+        assert_eq!(parse_character_class(&mut ts2),
+                   Ok(NegClass(~[Named(~"space"), Singles(~"_")])));
+    }
+
+    #[test]
+    fn test_bad_named_charclass() {
+        let term = [','];
+        let mut b1 = LookaheadBuffer::new(":digit'a']");
+        let mut ts1 = TokenStream::new(&mut b1, term);
+        assert!(parse_character_class(&mut ts1).is_err());
     }
 
     #[test]
@@ -376,22 +592,54 @@ mod tests {
         let term = [','];
         let mut b1 = LookaheadBuffer::new("letter,");
         let mut ts1 = TokenStream::new(&mut b1, term);
-        assert_eq!(parse_regexp(&mut ts1), Symb(~"letter"));
+        assert_eq!(parse_regexp(&mut ts1), Ok(Symb(~"letter")));
 
         let mut b2 = LookaheadBuffer::new("letter*,");
         let mut ts2 = TokenStream::new(&mut b2, term);
-        assert_eq!(parse_regexp(&mut ts2), Star(~Symb(~"letter")));
+        assert_eq!(parse_regexp(&mut ts2), Ok(Star(~Symb(~"letter"))));
 
         let mut b3 = LookaheadBuffer::new("letter (letter | digit)*,");
         let mut ts3 = TokenStream::new(&mut b3, term);
-        assert_eq!(parse_regexp(&mut ts3), 
-                   Conc(~Symb(~"letter"), ~Star(~Union(~Symb(~"letter"), ~Symb(~"digit")))));
-        assert_eq!(ts3.next_token(), End);
+        assert_eq!(parse_regexp(&mut ts3),
+                   Ok(Conc(~Symb(~"letter"), ~Star(~Union(~Symb(~"letter"), ~Symb(~"digit"))))));
+        assert_eq!(ts3.next_token(), Ok(End));
 
         let mut b4 = LookaheadBuffer::new("['0'-'9']+ '.' ['0'-'9']+,");
         let mut ts4 = TokenStream::new(&mut b4, term);
         assert_eq!(parse_regexp(&mut ts4),
-                   Conc(~OnePlus(~CharClass(~[Range('0', '9')])), 
-                        ~Conc(~Str(~"."), ~OnePlus(~CharClass(~[Range('0', '9')])))));
+                   Ok(Conc(~OnePlus(~CharClass(~[Range('0', '9')])),
+                           ~Conc(~Str(~"."), ~OnePlus(~CharClass(~[Range('0', '9')]))))));
+    }
+
+    #[test]
+    fn test_parse_optional() {
+        let term = [','];
+        let mut b1 = LookaheadBuffer::new("letter?,");
+        let mut ts1 = TokenStream::new(&mut b1, term);
+        assert_eq!(parse_regexp(&mut ts1), Ok(Opt(~Symb(~"letter"))));
+    }
+
+    #[test]
+    fn test_parse_bounded_repeat() {
+        let term = [','];
+        let mut b1 = LookaheadBuffer::new("letter{3},");
+        let mut ts1 = TokenStream::new(&mut b1, term);
+        assert_eq!(parse_regexp(&mut ts1), Ok(Repeat(~Symb(~"letter"), 3, Some(3))));
+
+        let mut b2 = LookaheadBuffer::new("letter{2,5},");
+        let mut ts2 = TokenStream::new(&mut b2, term);
+        assert_eq!(parse_regexp(&mut ts2), Ok(Repeat(~Symb(~"letter"), 2, Some(5))));
+
+        let mut b3 = LookaheadBuffer::new("letter{2,},");
+        let mut ts3 = TokenStream::new(&mut b3, term);
+        assert_eq!(parse_regexp(&mut ts3), Ok(Repeat(~Symb(~"letter"), 2, None)));
+    }
+
+    #[test]
+    fn test_parse_bad_repeat_bound() {
+        let term = [','];
+        let mut b1 = LookaheadBuffer::new("letter{5,2},");
+        let mut ts1 = TokenStream::new(&mut b1, term);
+        assert!(parse_regexp(&mut ts1).is_err());
     }
 }